@@ -12,6 +12,25 @@ use crate::{Agent, State};
 use crate::controllers::Request;
 use vcx::aries::messages::a2a::A2AMessage;
 use vcx::aries::messages::issuance::credential_offer::CredentialOffer as VcxCredentialOffer;
+use vcx::aries::handlers::issuance::v2::issuer::issuer::Issuer as IssuerV2;
+use vcx::aries::handlers::issuance::v2::holder::holder::Holder as HolderV2;
+use vcx::aries::messages::issuance::v2::offer_credential::OfferCredential as OfferCredentialV2;
+
+const ISSUANCE_V1: &str = "1.0";
+const ISSUANCE_V2: &str = "2.0";
+pub(crate) const ISSUANCE_OID4VCI: &str = "oid4vci";
+
+pub(crate) fn _version_key(id: &str) -> String {
+    format!("{}/version", id)
+}
+
+fn _revocation_key(id: &str) -> String {
+    format!("{}/revoked", id)
+}
+
+fn _proposal_key(id: &str) -> String {
+    format!("{}/proposal-sent", id)
+}
 
 #[derive(Serialize, Deserialize, Default)]
 struct CredentialPreview {
@@ -24,10 +43,61 @@ struct CredentialPreview {
 struct CredentialOffer {
     cred_def_id: String,
     credential_preview: CredentialPreview,
+    connection_id: String,
+    #[serde(default)]
+    rev_reg_id: Option<String>,
+    #[serde(default)]
+    tails_file: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CredentialProposal {
+    cred_def_id: String,
+    schema_id: String,
+    credential_proposal: CredentialPreview,
     connection_id: String
 }
 
-fn _get_state_issuer(issuer: &Issuer) -> State {
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct CredentialFormatAttachment {
+    format: String,
+    #[serde(default)]
+    cred_def_id: Option<String>,
+    #[serde(default)]
+    data: serde_json::Value
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CredentialOfferV2 {
+    connection_id: String,
+    credential_preview: CredentialPreview,
+    formats: Vec<CredentialFormatAttachment>
+}
+
+fn _get_state_issuer(issuer: &Issuer, revoked: bool) -> State {
+    if revoked {
+        return State::CredentialRevoked;
+    }
+    match VcxStateType::from_u32(issuer.get_state().unwrap()) {
+        VcxStateType::VcxStateInitialized => State::Initial,
+        VcxStateType::VcxStateOfferSent => State::OfferSent,
+        VcxStateType::VcxStateRequestReceived => State::RequestReceived,
+        VcxStateType::VcxStateAccepted => State::CredentialSent,
+        _ => State::Unknown
+    }
+}
+
+fn _get_state_holder(holder: &Holder, proposal_sent: bool) -> State {
+    match VcxStateType::from_u32(holder.get_status()) {
+        VcxStateType::VcxStateInitialized if proposal_sent => State::ProposalSent,
+        VcxStateType::VcxStateRequestReceived => State::OfferReceived,
+        VcxStateType::VcxStateOfferSent => State::RequestSent,
+        VcxStateType::VcxStateAccepted => State::CredentialReceived,
+        _ => State::Unknown
+    }
+}
+
+fn _get_state_issuer_v2(issuer: &IssuerV2) -> State {
     match VcxStateType::from_u32(issuer.get_state().unwrap()) {
         VcxStateType::VcxStateInitialized => State::Initial,
         VcxStateType::VcxStateOfferSent => State::OfferSent,
@@ -37,7 +107,7 @@ fn _get_state_issuer(issuer: &Issuer) -> State {
     }
 }
 
-fn _get_state_holder(holder: &Holder) -> State {
+fn _get_state_holder_v2(holder: &HolderV2) -> State {
     match VcxStateType::from_u32(holder.get_status()) {
         VcxStateType::VcxStateRequestReceived => State::OfferReceived,
         VcxStateType::VcxStateOfferSent => State::RequestSent,
@@ -46,6 +116,18 @@ fn _get_state_holder(holder: &Holder) -> State {
     }
 }
 
+fn _flatten_attributes(values: &serde_json::Value) -> serde_json::Value {
+    match values.as_object() {
+        Some(map) => {
+            let flattened: serde_json::Map<String, serde_json::Value> = map.iter()
+                .map(|(attr, value)| (attr.clone(), value["raw"].clone()))
+                .collect();
+            serde_json::Value::Object(flattened)
+        }
+        None => serde_json::Value::Null
+    }
+}
+
 impl Agent {
     pub fn send_credential_offer(&mut self, cred_offer: &CredentialOffer) -> HarnessResult<String> {
         let id = uuid::Uuid::new_v4().to_string();
@@ -53,8 +135,8 @@ impl Agent {
             .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Connection with id {} not found", id)))?;
         let issuer_config = IssuerConfig {
             cred_def_id: cred_offer.cred_def_id.clone(),
-            rev_reg_id: None,
-            tails_file: None
+            rev_reg_id: cred_offer.rev_reg_id.clone(),
+            tails_file: cred_offer.tails_file.clone()
         };
         let credential_preview = serde_json::to_string(&cred_offer.credential_preview).map_err(|err| HarnessError::from(err))?;
         let mut issuer = Issuer::create(&issuer_config, &credential_preview, &id).map_err(|err| HarnessError::from(err))?;
@@ -70,20 +152,58 @@ impl Agent {
             .ok_or(HarnessError::from_msg(HarnessErrorType::InternalServerError, &format!("No connection established")))?;
         // TODO: Sends problem report saying schema id is invalid
         holder.send_request(connection.agent_info().pw_did.to_string(), connection.send_message_closure().map_err(|err| HarnessError::from(err))?).map_err(|err| HarnessError::from(err))?;
-        let state = _get_state_holder(&holder);
+        let proposal_sent = self.db.get::<bool>(&_proposal_key(id)).unwrap_or(false);
+        let state = _get_state_holder(&holder, proposal_sent);
         Ok(json!({ "state": state }).to_string())
     }
 
-    pub fn get_issuer_state(&mut self, id: &str) -> HarnessResult<String> {
+    pub fn send_credential_proposal(&mut self, proposal: &CredentialProposal) -> HarnessResult<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let connection: Connection = self.db.get(&proposal.connection_id)
+            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Connection with id {} not found", id)))?;
+        let credential_proposal = serde_json::to_string(&proposal.credential_proposal).map_err(|err| HarnessError::from(err))?;
+        let mut holder = Holder::create_proposal(&proposal.cred_def_id, &proposal.schema_id, &credential_proposal, &id).map_err(|err| HarnessError::from(err))?;
+        holder.send_proposal(connection.send_message_closure().map_err(|err| HarnessError::from(err))?).map_err(|err| HarnessError::from(err))?;
+        self.db.set(&id, &holder).map_err(|err| HarnessError::from(err))?;
+        self.db.set(&_proposal_key(&id), &true).map_err(|err| HarnessError::from(err))?;
+        Ok(json!({ "state": "proposal-sent", "thread_id": id }).to_string())
+    }
+
+    pub fn send_credential_offer_v2(&mut self, cred_offer: &CredentialOfferV2) -> HarnessResult<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let connection: Connection = self.db.get(&cred_offer.connection_id)
+            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Connection with id {} not found", id)))?;
+        let formats = serde_json::to_string(&cred_offer.formats).map_err(|err| HarnessError::from(err))?;
+        let credential_preview = serde_json::to_string(&cred_offer.credential_preview).map_err(|err| HarnessError::from(err))?;
+        let mut issuer = IssuerV2::create(&formats, &credential_preview, &id).map_err(|err| HarnessError::from(err))?;
+        issuer.send_credential_offer(connection.send_message_closure().map_err(|err| HarnessError::from(err))?, None).map_err(|err| HarnessError::from(err))?;
+        self.db.set(&id, &issuer).map_err(|err| HarnessError::from(err))?;
+        self.db.set(&_version_key(&id), &ISSUANCE_V2.to_string()).map_err(|err| HarnessError::from(err))?;
+        Ok(json!({ "state": "offer-sent", "thread_id": id }).to_string()) // TODO: This must really be a thread id
+    }
+
+    pub fn send_credential_request_v2(&mut self, id: &str) -> HarnessResult<String> {
+        let mut holder: HolderV2 = self.db.get(id)
+            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Holder with id {} not found", id)))?;
+        let connection = self.last_connection.as_ref()
+            .ok_or(HarnessError::from_msg(HarnessErrorType::InternalServerError, &format!("No connection established")))?;
+        holder.send_request(connection.agent_info().pw_did.to_string(), connection.send_message_closure().map_err(|err| HarnessError::from(err))?).map_err(|err| HarnessError::from(err))?;
+        let state = _get_state_holder_v2(&holder);
+        Ok(json!({ "state": state }).to_string())
+    }
+
+    fn _get_issuer_state_v1(&mut self, id: &str) -> HarnessResult<String> {
         match self.db.get::<Issuer>(id) {
             Some(issuer) => {
-                let state = _get_state_issuer(&issuer);
+                let revoked = self.db.get::<bool>(&_revocation_key(id)).unwrap_or(false);
+                let state = _get_state_issuer(&issuer, revoked);
                 Ok(json!({ "state": state }).to_string())
             }
             None => {
                 match self.db.get::<Holder>(id) {
                     Some(holder) => {
-                        let state = _get_state_holder(&holder);
+                        let proposal_sent = self.db.get::<bool>(&_proposal_key(id)).unwrap_or(false);
+                        let state = _get_state_holder(&holder, proposal_sent);
                         Ok(json!({ "state": state }).to_string())
                     }
                     None => {
@@ -98,14 +218,98 @@ impl Agent {
                                 }
                             })
                             .collect();
-                        let holder = Holder::create(credential_offers.last().unwrap().clone(), id).map_err(|err| HarnessError::from(err))?;
+                        let cred_offer = credential_offers.last()
+                            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Issuer or Holder with id {} not found", id)))?;
+                        let holder = Holder::create(cred_offer.clone(), id).map_err(|err| HarnessError::from(err))?;
                         self.db.set(&id, &holder).map_err(|err| HarnessError::from(err))?;
+                        self.db.set(&_version_key(id), &ISSUANCE_V1.to_string()).map_err(|err| HarnessError::from(err))?;
                         Ok(json!({ "state": "offer-received" }).to_string())
                     }
                 }
             }
         }
     }
+
+    fn _get_issuer_state_v2(&mut self, id: &str) -> HarnessResult<String> {
+        match self.db.get::<IssuerV2>(id) {
+            Some(issuer) => {
+                let state = _get_state_issuer_v2(&issuer);
+                Ok(json!({ "state": state }).to_string())
+            }
+            None => {
+                match self.db.get::<HolderV2>(id) {
+                    Some(holder) => {
+                        let state = _get_state_holder_v2(&holder);
+                        Ok(json!({ "state": state }).to_string())
+                    }
+                    None => {
+                        let connection = self.last_connection.as_ref()
+                            .ok_or(HarnessError::from_msg(HarnessErrorType::InternalServerError, &format!("No connection established")))?;
+                        let credential_offers: Vec<OfferCredentialV2> = connection.get_messages()?
+                            .into_iter()
+                            .filter_map(|(_, a2a_message)| {
+                                match a2a_message {
+                                    A2AMessage::OfferCredentialV2(cred_offer) => Some(cred_offer),
+                                    _ => None
+                                }
+                            })
+                            .collect();
+                        let cred_offer = credential_offers.last()
+                            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Issuer or Holder with id {} not found", id)))?;
+                        let holder = HolderV2::create(cred_offer.clone(), id).map_err(|err| HarnessError::from(err))?;
+                        self.db.set(&id, &holder).map_err(|err| HarnessError::from(err))?;
+                        self.db.set(&_version_key(id), &ISSUANCE_V2.to_string()).map_err(|err| HarnessError::from(err))?;
+                        Ok(json!({ "state": "offer-received" }).to_string())
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn revoke_credential(&mut self, id: &str) -> HarnessResult<String> {
+        let issuer: Issuer = self.db.get(id)
+            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Issuer with id {} not found", id)))?;
+        let rev_reg_id = issuer.get_rev_reg_id().map_err(|err| HarnessError::from(err))?;
+        let cred_rev_id = issuer.get_rev_id().map_err(|err| HarnessError::from(err))?;
+        let tails_file = issuer.get_tails_file().map_err(|err| HarnessError::from(err))?;
+        anoncreds::revoke_credential(&tails_file, &rev_reg_id, &cred_rev_id).map_err(|err| HarnessError::from(err))?;
+        self.db.set(&_revocation_key(id), &true).map_err(|err| HarnessError::from(err))?;
+        Ok(json!({ "state": "revoked" }).to_string())
+    }
+
+    fn _get_credential_attachment(&self, id: &str) -> HarnessResult<String> {
+        if let Some(holder) = self.db.get::<Holder>(id) {
+            return holder.get_credential().map_err(|err| HarnessError::from(err));
+        }
+        if let Some(holder) = self.db.get::<HolderV2>(id) {
+            return holder.get_credential().map_err(|err| HarnessError::from(err));
+        }
+        Err(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("Holder with id {} not found", id)))
+    }
+
+    pub fn get_credential_attachment(&self, id: &str) -> HarnessResult<String> {
+        let attachment = self._get_credential_attachment(id)?;
+        Ok(json!({ "attachment": attachment }).to_string())
+    }
+
+    pub fn get_credential_attributes(&self, id: &str) -> HarnessResult<String> {
+        let attachment = self._get_credential_attachment(id)?;
+        let credential: serde_json::Value = serde_json::from_str(&attachment).map_err(|err| HarnessError::from(err))?;
+        let attributes = _flatten_attributes(&credential["values"]);
+        Ok(json!({ "attributes": attributes }).to_string())
+    }
+
+    pub fn get_issuer_state(&mut self, id: &str) -> HarnessResult<String> {
+        match self.db.get::<String>(&_version_key(id)) {
+            Some(version) if version == ISSUANCE_V2 => self._get_issuer_state_v2(id),
+            Some(version) if version == ISSUANCE_OID4VCI => self.get_openid4vci_state(id),
+            _ => self._get_issuer_state_v1(id)
+        }
+    }
+
+    pub fn get_issuer_state_v2(&mut self, id: &str) -> HarnessResult<String> {
+        self._get_issuer_state_v2(id)
+    }
 }
 
 #[post("/send-offer")]
@@ -118,12 +322,48 @@ pub async fn send_credential_request(req: web::Json<Request<String>>, agent: web
     agent.lock().unwrap().send_credential_request(&req.id)
 }
 
+#[post("/send-proposal")]
+pub async fn send_credential_proposal(req: web::Json<Request<CredentialProposal>>, agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().send_credential_proposal(&req.data)
+}
+
+#[post("/revoke")]
+pub async fn revoke_credential(req: web::Json<Request<String>>, agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().revoke_credential(&req.id)
+}
+
+#[get("/{id}/attachment")]
+pub async fn get_credential_attachment(agent: web::Data<Mutex<Agent>>, path: web::Path<String>) -> impl Responder {
+    agent.lock().unwrap().get_credential_attachment(&path.into_inner())
+}
+
+#[get("/{id}/attachment/attributes")]
+pub async fn get_credential_attributes(agent: web::Data<Mutex<Agent>>, path: web::Path<String>) -> impl Responder {
+    agent.lock().unwrap().get_credential_attributes(&path.into_inner())
+}
+
 #[get("/{issuer_id}")]
 pub async fn get_issuer_state(agent: web::Data<Mutex<Agent>>, path: web::Path<String>) -> impl Responder {
     agent.lock().unwrap().get_issuer_state(&path.into_inner())
         .with_header("Cache-Control", "private, no-store, must-revalidate")
 }
 
+#[get("/{issuer_id}")]
+pub async fn get_issuer_state_v2(agent: web::Data<Mutex<Agent>>, path: web::Path<String>) -> impl Responder {
+    agent.lock().unwrap().get_issuer_state_v2(&path.into_inner())
+        .with_header("Cache-Control", "private, no-store, must-revalidate")
+}
+
+#[post("/send-offer")]
+pub async fn send_credential_offer_v2(req: web::Json<Request<CredentialOfferV2>>, agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().send_credential_offer_v2(&req.data)
+}
+
+#[post("/send-request")]
+pub async fn send_credential_request_v2(req: web::Json<Request<String>>, agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().send_credential_request_v2(&req.id)
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg
         .service(
@@ -131,5 +371,15 @@ pub fn config(cfg: &mut web::ServiceConfig) {
                 .service(send_credential_offer)
                 .service(get_issuer_state)
                 .service(send_credential_request)
+                .service(send_credential_proposal)
+                .service(get_credential_attachment)
+                .service(get_credential_attributes)
+                .service(revoke_credential)
+        )
+        .service(
+            web::scope("/command/issue-credential-v2")
+                .service(send_credential_offer_v2)
+                .service(get_issuer_state_v2)
+                .service(send_credential_request_v2)
         );
 }
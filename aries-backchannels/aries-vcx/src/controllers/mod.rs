@@ -0,0 +1,3 @@
+pub mod issuance;
+pub mod trustping;
+pub mod openid4vci;
@@ -0,0 +1,177 @@
+use std::sync::Mutex;
+use actix_web::{web, Responder, post, get};
+use crate::error::{HarnessError, HarnessErrorType, HarnessResult};
+use uuid;
+use crate::{Agent, State};
+use crate::controllers::Request;
+use crate::controllers::issuance::{_version_key, ISSUANCE_OID4VCI};
+
+#[derive(Serialize, Deserialize, Default)]
+struct CredentialOfferOID4VCI {
+    credential_issuer: String,
+    credential_configuration_id: String,
+    credential_preview: serde_json::Value,
+    #[serde(default)]
+    by_reference: bool
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct TokenRequest {
+    grant_type: String,
+    #[serde(rename = "pre-authorized_code")]
+    pre_authorized_code: String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Oid4vciSession {
+    credential_issuer: String,
+    credential_configuration_id: String,
+    credential_preview: serde_json::Value,
+    issued: bool
+}
+
+const PRE_AUTHORIZED_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:pre-authorized_code";
+const SUPPORTED_CONFIGURATIONS_KEY: &str = "oid4vci/credential-configurations-supported";
+
+fn _get_state_oid4vci(session: &Oid4vciSession) -> State {
+    if session.issued {
+        State::CredentialSent
+    } else {
+        State::OfferSent
+    }
+}
+
+impl Agent {
+    fn _register_configuration_supported(&mut self, credential_configuration_id: &str) -> HarnessResult<()> {
+        let mut configurations = self.db.get::<serde_json::Value>(SUPPORTED_CONFIGURATIONS_KEY)
+            .and_then(|value| value.as_object().cloned())
+            .unwrap_or_default();
+        configurations.insert(credential_configuration_id.to_string(), json!({ "format": "jwt_vc_json" }));
+        self.db.set(SUPPORTED_CONFIGURATIONS_KEY, &serde_json::Value::Object(configurations)).map_err(|err| HarnessError::from(err))
+    }
+
+    pub fn create_openid4vci_offer(&mut self, offer: &CredentialOfferOID4VCI) -> HarnessResult<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let session = Oid4vciSession {
+            credential_issuer: offer.credential_issuer.clone(),
+            credential_configuration_id: offer.credential_configuration_id.clone(),
+            credential_preview: offer.credential_preview.clone(),
+            issued: false
+        };
+        self.db.set(&id, &session).map_err(|err| HarnessError::from(err))?;
+        self.db.set(&_version_key(&id), &ISSUANCE_OID4VCI.to_string()).map_err(|err| HarnessError::from(err))?;
+        self._register_configuration_supported(&offer.credential_configuration_id)?;
+        let credential_offer = json!({
+            "credential_issuer": offer.credential_issuer,
+            "credential_configuration_ids": [offer.credential_configuration_id],
+            "grants": {
+                (PRE_AUTHORIZED_CODE_GRANT_TYPE): { "pre-authorized_code": id }
+            }
+        });
+        if offer.by_reference {
+            Ok(json!({
+                "state": "offer-sent",
+                "thread_id": id,
+                "credential_offer_uri": format!("/command/openid4vci/offers/{}", id)
+            }).to_string())
+        } else {
+            Ok(json!({
+                "state": "offer-sent",
+                "thread_id": id,
+                "credential_offer": credential_offer
+            }).to_string())
+        }
+    }
+
+    pub fn get_openid4vci_offer(&self, id: &str) -> HarnessResult<String> {
+        let session: Oid4vciSession = self.db.get(id)
+            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("OpenID4VCI session with id {} not found", id)))?;
+        Ok(json!({
+            "credential_issuer": session.credential_issuer,
+            "credential_configuration_ids": [session.credential_configuration_id],
+            "grants": {
+                (PRE_AUTHORIZED_CODE_GRANT_TYPE): { "pre-authorized_code": id }
+            }
+        }).to_string())
+    }
+
+    pub fn issue_openid4vci_credential(&mut self, token_request: &TokenRequest) -> HarnessResult<String> {
+        if token_request.grant_type != PRE_AUTHORIZED_CODE_GRANT_TYPE {
+            return Err(HarnessError::from_msg(HarnessErrorType::BadRequestError, &format!("Unsupported grant_type: {}", token_request.grant_type)));
+        }
+        let pre_authorized_code = &token_request.pre_authorized_code;
+        let mut session: Oid4vciSession = self.db.get(pre_authorized_code)
+            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("OpenID4VCI session with code {} not found", pre_authorized_code)))?;
+        session.issued = true;
+        self.db.set(pre_authorized_code, &session).map_err(|err| HarnessError::from(err))?;
+        let access_token = uuid::Uuid::new_v4().to_string();
+        Ok(json!({
+            "access_token": access_token,
+            "token_type": "bearer",
+            "expires_in": 300,
+            "credential": session.credential_preview
+        }).to_string())
+    }
+
+    pub(crate) fn get_openid4vci_state(&self, id: &str) -> HarnessResult<String> {
+        let session: Oid4vciSession = self.db.get(id)
+            .ok_or(HarnessError::from_msg(HarnessErrorType::NotFoundError, &format!("OpenID4VCI session with id {} not found", id)))?;
+        let state = _get_state_oid4vci(&session);
+        Ok(json!({ "state": state }).to_string())
+    }
+
+    pub fn openid4vci_issuer_metadata(&self) -> HarnessResult<String> {
+        let credential_configurations_supported = self.db.get::<serde_json::Value>(SUPPORTED_CONFIGURATIONS_KEY)
+            .unwrap_or(json!({}));
+        Ok(json!({
+            "credential_issuer": "/",
+            "credential_endpoint": "/command/openid4vci/token",
+            "credential_configurations_supported": credential_configurations_supported
+        }).to_string())
+    }
+
+    pub fn openid4vci_authorization_server_metadata(&self) -> HarnessResult<String> {
+        Ok(json!({
+            "issuer": "/",
+            "token_endpoint": "/command/openid4vci/token",
+            "grant_types_supported": [PRE_AUTHORIZED_CODE_GRANT_TYPE]
+        }).to_string())
+    }
+}
+
+#[post("/offers")]
+pub async fn create_offer(req: web::Json<Request<CredentialOfferOID4VCI>>, agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().create_openid4vci_offer(&req.data)
+}
+
+#[get("/offers/{id}")]
+pub async fn get_offer(agent: web::Data<Mutex<Agent>>, path: web::Path<String>) -> impl Responder {
+    agent.lock().unwrap().get_openid4vci_offer(&path.into_inner())
+}
+
+#[post("/token")]
+pub async fn token(req: web::Json<TokenRequest>, agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().issue_openid4vci_credential(&req)
+}
+
+#[get("/.well-known/openid-credential-issuer")]
+pub async fn well_known_credential_issuer(agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().openid4vci_issuer_metadata()
+}
+
+#[get("/.well-known/oauth-authorization-server")]
+pub async fn well_known_oauth_authorization_server(agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().openid4vci_authorization_server_metadata()
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(well_known_credential_issuer)
+        .service(well_known_oauth_authorization_server)
+        .service(
+            web::scope("/command/openid4vci")
+                .service(create_offer)
+                .service(get_offer)
+                .service(token)
+        );
+}
@@ -0,0 +1,72 @@
+use std::sync::Mutex;
+use actix_web::{web, Responder, post, get};
+use crate::error::{HarnessError, HarnessErrorType, HarnessResult};
+use vcx::aries::messages::a2a::A2AMessage;
+use vcx::aries::messages::trust_ping::ping::Ping;
+use vcx::aries::messages::trust_ping::ping_response::PingResponse;
+use uuid;
+use crate::{Agent, State};
+
+fn _ping_received_key(thread_id: &str) -> String {
+    format!("{}/ping-response", thread_id)
+}
+
+impl Agent {
+    pub fn send_ping(&mut self) -> HarnessResult<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let connection = self.last_connection.clone()
+            .ok_or(HarnessError::from_msg(HarnessErrorType::InternalServerError, &format!("No connection established")))?;
+        let ping = Ping::create().set_thread_id(&id).set_response_requested(true);
+        connection.send_message(&ping.to_a2a_message()).map_err(|err| HarnessError::from(err))?;
+        Ok(json!({ "state": "ping-sent", "thread_id": id }).to_string())
+    }
+
+    pub fn get_ping_state(&mut self, id: &str) -> HarnessResult<String> {
+        let connection = self.last_connection.clone()
+            .ok_or(HarnessError::from_msg(HarnessErrorType::InternalServerError, &format!("No connection established")))?;
+        for (_, a2a_message) in connection.get_messages()?.into_iter() {
+            match a2a_message {
+                A2AMessage::Ping(ping) => {
+                    let key = _ping_received_key(ping.get_thread_id());
+                    if !self.db.get::<bool>(&key).unwrap_or(false) {
+                        if ping.response_requested {
+                            let response = PingResponse::create().set_thread_id(ping.get_thread_id());
+                            connection.send_message(&response.to_a2a_message()).map_err(|err| HarnessError::from(err))?;
+                        }
+                        self.db.set(&key, &true).map_err(|err| HarnessError::from(err))?;
+                    }
+                }
+                A2AMessage::PingResponse(response) => {
+                    let key = _ping_received_key(response.get_thread_id());
+                    if !self.db.get::<bool>(&key).unwrap_or(false) {
+                        self.db.set(&key, &true).map_err(|err| HarnessError::from(err))?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let responded = self.db.get::<bool>(&_ping_received_key(id)).unwrap_or(false);
+        let state = if responded { State::Done } else { State::Initial };
+        Ok(json!({ "state": state }).to_string())
+    }
+}
+
+#[post("/send-ping")]
+pub async fn send_ping(agent: web::Data<Mutex<Agent>>) -> impl Responder {
+    agent.lock().unwrap().send_ping()
+}
+
+#[get("/{id}")]
+pub async fn get_ping_state(agent: web::Data<Mutex<Agent>>, path: web::Path<String>) -> impl Responder {
+    agent.lock().unwrap().get_ping_state(&path.into_inner())
+        .with_header("Cache-Control", "private, no-store, must-revalidate")
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(
+            web::scope("/command/trust-ping")
+                .service(send_ping)
+                .service(get_ping_state)
+        );
+}